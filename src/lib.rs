@@ -0,0 +1,2142 @@
+use core::fmt;
+use std::any::Any;
+use std::io::{Cursor, Write};
+use std::ops::Deref;
+
+pub use gfx_maths::{Mat4, Vec3};
+use image::{DynamicImage, GenericImage, ImageBuffer, ImageOutputFormat, ImageResult, Luma, Rgba};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    image: DynamicImage,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Canvas {
+        Canvas {
+            width,
+            height,
+            // starts out fully transparent
+            image: DynamicImage::new_rgba8(width, height),
+        }
+    }
+
+    pub fn draw(&mut self, x: u32, y: u32, color: Color) {
+        if x >= self.width || y >= self.height {
+            panic!(
+                "drawing outside of canvas: point ({}, {}) outside dimensions ({}/{})",
+                x, y, self.width, self.height
+            );
+        }
+
+        let pixel = color.to_rgba();
+        self.image.put_pixel(x, y, pixel);
+    }
+
+    pub fn draw_area(&mut self, x: u32, y: u32, width: u32, colors: &[Color]) {
+        if x + width > self.width || y + colors.len() as u32 / width > self.height {
+            panic!(
+                "drawing outside of canvas: drawing area ({}-{}, {}-{}) outside dimensions ({}/{})",
+                x,
+                width,
+                y,
+                colors.len() as u32 / width,
+                self.width,
+                self.height
+            );
+        }
+
+        for (i, color) in colors.iter().enumerate() {
+            let x = x + (i as u32 % width);
+            let y = y + (i as u32 / width);
+            self.draw(x, y, *color);
+        }
+    }
+
+    /// Copies the pixels of another canvas onto this one, with its top left corner at (`x`, `y`)
+    pub fn overlay(&mut self, x: u32, y: u32, other: &Canvas) {
+        if x + other.width > self.width || y + other.height > self.height {
+            panic!(
+                "drawing outside of canvas: overlay ({}-{}, {}-{}) outside dimensions ({}/{})",
+                x,
+                x + other.width,
+                y,
+                y + other.height,
+                self.width,
+                self.height
+            );
+        }
+
+        self.image.copy_from(&other.image, x, y).unwrap();
+    }
+
+    /// Arranges the given canvases into a grid with `columns` cells per row, e.g. to compare renders side by side
+    ///
+    /// Every cell is as big as the largest canvas; smaller canvases are placed in the top left corner of their cell
+    pub fn contact_sheet(canvases: &[Canvas], columns: u32) -> Canvas {
+        assert!(columns > 0, "a contact sheet needs at least one column");
+
+        let cell_width = canvases.iter().map(|c| c.width).max().unwrap_or(0);
+        let cell_height = canvases.iter().map(|c| c.height).max().unwrap_or(0);
+        let rows = (canvases.len() as u32).div_ceil(columns);
+        let columns = columns.min(canvases.len() as u32);
+
+        let mut sheet = Canvas::new(columns * cell_width, rows * cell_height);
+        for (i, canvas) in canvases.iter().enumerate() {
+            let x = (i as u32 % columns) * cell_width;
+            let y = (i as u32 / columns) * cell_height;
+            sheet.overlay(x, y, canvas);
+        }
+        sheet
+    }
+
+    /// Encodes the canvas in the given format and writes it to `writer`
+    ///
+    /// Unlike `save`, this works with any writer, e.g. stdout or a network socket
+    pub fn write_to<W: Write>(&self, mut writer: W, format: ImageOutputFormat) -> ImageResult<()> {
+        // some encoders need to seek, so encode into memory first
+        let mut encoded = Cursor::new(Vec::new());
+        self.image.write_to(&mut encoded, format)?;
+        writer.write_all(encoded.get_ref())?;
+        Ok(())
+    }
+
+    pub fn save(self, filename: &str) {
+        self.save_ref(filename).unwrap();
+    }
+
+    /// Saves the canvas without consuming it, e.g. to save intermediate results and keep drawing onto the same canvas
+    pub fn save_ref(&self, filename: &str) -> ImageResult<()> {
+        self.image.save(filename)
+    }
+
+    pub fn get_dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+impl Default for Canvas {
+    fn default() -> Canvas {
+        Canvas::new(800, 600)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Color {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Color {
+    pub fn new(red: u8, green: u8, blue: u8) -> Color {
+        Color { red, green, blue }
+    }
+
+    /// Interpolates between two colors component-wise, in the (sRGB) space they are stored in
+    pub fn lerp(self, other: Color, t: f32) -> Color {
+        let lerp = |a: u8, b: u8| (a as f32 * (1.0 - t) + b as f32 * t).round() as u8;
+        Color::new(
+            lerp(self.red, other.red),
+            lerp(self.green, other.green),
+            lerp(self.blue, other.blue),
+        )
+    }
+
+    /// Interpolates between two colors in linear light, which avoids the dull midtones of interpolating in sRGB
+    pub fn lerp_linear(self, other: Color, t: f32) -> Color {
+        self.lerp_linear_dithered(other, t, 0.0)
+    }
+
+    /// Like `lerp_linear`, but adds `dither` (in units of the last bit, see `bayer_dither`) before rounding to 8 bits
+    fn lerp_linear_dithered(self, other: Color, t: f32, dither: f32) -> Color {
+        let lerp = |a: u8, b: u8| {
+            let linear = srgb_to_linear(a) * (1.0 - t) + srgb_to_linear(b) * t;
+            linear_to_srgb(linear, dither)
+        };
+        Color::new(
+            lerp(self.red, other.red),
+            lerp(self.green, other.green),
+            lerp(self.blue, other.blue),
+        )
+    }
+
+    fn to_rgba(self) -> Rgba<u8> {
+        Rgba([self.red, self.green, self.blue, 255])
+    }
+}
+
+// https://en.wikipedia.org/wiki/SRGB#Transformation
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a linear intensity to an 8-bit sRGB value, adding `dither` (in units of the last bit) before rounding
+fn linear_to_srgb(value: f32, dither: f32) -> u8 {
+    let value = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (value.clamp(0.0, 1.0) * 255.0 + dither)
+        .round()
+        .clamp(0.0, 255.0) as u8
+}
+
+/// Returns an ordered dither offset between -0.5 and 0.5 for the pixel at (`x`, `y`), from a 4x4 Bayer matrix
+///
+/// Spreads the rounding error of smooth gradients over neighbouring pixels, which hides the banding of 8-bit output
+fn bayer_dither(x: u32, y: u32) -> f32 {
+    const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+    (BAYER[y as usize % 4][x as usize % 4] as f32 + 0.5) / 16.0 - 0.5
+}
+
+#[derive(Debug)]
+pub struct Raytracer {
+    pub scene: Scene,
+    /// Restricts rendering to some layers of the scene; renders everything if `None`
+    pub layer_filter: Option<LayerFilter>,
+    /// Width of a pixel relative to its height, for displays with non-square pixels
+    ///
+    /// With a pixel aspect of 2.0, every pixel covers twice as much of the scene horizontally, so that the image looks correctly proportioned once the display stretches it.
+    /// It has to be finite and positive; rendering panics otherwise
+    pub pixel_aspect: f32,
+    /// What is drawn where the rays miss the scene
+    pub background: Background,
+    /// Dithers the background before it is quantized to 8 bits, which avoids visible banding in the gradient
+    pub dither: bool,
+}
+
+impl Raytracer {
+    pub fn new(scene: Scene) -> Raytracer {
+        Raytracer {
+            scene,
+            layer_filter: None,
+            pixel_aspect: 1.0,
+            background: Background::default(),
+            dither: false,
+        }
+    }
+
+    /// Renders the scene onto a new canvas of the given dimensions and writes it to `writer`, encoded in the given format
+    pub fn render_and_encode<W: Write>(
+        &self,
+        (width, height): (u32, u32),
+        format: ImageOutputFormat,
+        writer: W,
+    ) -> ImageResult<()> {
+        let mut canvas = Canvas::new(width, height);
+        self.render(&mut canvas);
+        canvas.write_to(writer, format)
+    }
+
+    pub fn render(&self, canvas: &mut Canvas) {
+        self.check_pixel_aspect();
+        let (width, height) = canvas.get_dimensions();
+        for x in 0..width {
+            for y in 0..height {
+                if let Some(color) = self.shade(x, y, height) {
+                    canvas.draw(x, y, color);
+                }
+            }
+        }
+    }
+
+    /// Renders the scene into a caller-provided buffer of `width * height` colors, stored row by row
+    ///
+    /// Misses are filled with the background. As `Color` has no alpha, a transparent background is rejected instead of leaving the previous contents of the buffer in place
+    pub fn render_into(
+        &self,
+        (width, height): (u32, u32),
+        out: &mut [Color],
+    ) -> Result<(), RenderError> {
+        // computed in `usize`, as `width * height` can overflow `u32`
+        let expected = width as usize * height as usize;
+        if out.len() != expected {
+            return Err(RenderError::BufferSize {
+                expected,
+                actual: out.len(),
+            });
+        }
+        if self.background == Background::Transparent {
+            return Err(RenderError::TransparentBackground);
+        }
+        self.check_pixel_aspect();
+
+        for y in 0..height {
+            for x in 0..width {
+                if let Some(color) = self.shade(x, y, height) {
+                    out[y as usize * width as usize + x as usize] = color;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders the distance from the image plane to the closest hit of every pixel, stored row by row
+    ///
+    /// Misses are `f32::INFINITY`. Every pixel holds the depth of its own ray, so edges never average across a discontinuity.
+    /// See `encode_depth_png` to store the result
+    pub fn render_depth(&self, (width, height): (u32, u32)) -> Vec<f32> {
+        self.check_pixel_aspect();
+        let mut depth = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let hit = self
+                    .scene
+                    .intersect_filtered(&self.primary_ray(x, y), self.layer_filter.as_ref());
+                depth.push(hit.map_or(f32::INFINITY, |(_, t)| t));
+            }
+        }
+        depth
+    }
+
+    /// Returns the color of the pixel at (`x`, `y`), falling back to the background if its ray misses the scene
+    ///
+    /// Returns `None` for pixels that should stay transparent
+    fn shade(&self, x: u32, y: u32, height: u32) -> Option<Color> {
+        self.trace(x, y).or_else(|| {
+            if self.dither {
+                self.background.dithered_color_at(x, y, height)
+            } else {
+                self.background.color_at(y, height)
+            }
+        })
+    }
+
+    /// Returns the color of the pixel at (`x`, `y`), or `None` if its ray misses the scene
+    fn trace(&self, x: u32, y: u32) -> Option<Color> {
+        self.scene
+            .intersect_filtered(&self.primary_ray(x, y), self.layer_filter.as_ref())
+            .map(|_| Color::new(0, 255, 0))
+    }
+
+    fn check_pixel_aspect(&self) {
+        // zero would send every column through the same ray, a negative value mirrors the scene away, and NaN poisons every ray
+        if !(self.pixel_aspect.is_finite() && self.pixel_aspect > 0.0) {
+            panic!("invalid pixel aspect {}", self.pixel_aspect);
+        }
+    }
+
+    fn primary_ray(&self, x: u32, y: u32) -> Ray {
+        Ray::new(
+            Vec3::new(x as f32 * self.pixel_aspect, y as f32, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        )
+    }
+}
+
+/// Encodes a depth pass (see `Raytracer::render_depth`) as a 16-bit grayscale PNG and writes it to `writer`
+///
+/// Depths are mapped linearly from `near` (black) to `far` (white); anything outside, including misses, is clamped
+pub fn encode_depth_png<W: Write>(
+    (width, height): (u32, u32),
+    depth: &[f32],
+    (near, far): (f32, f32),
+    mut writer: W,
+) -> ImageResult<()> {
+    assert_eq!(
+        depth.len(),
+        width as usize * height as usize,
+        "depth pass does not match dimensions ({}/{})",
+        width,
+        height
+    );
+    assert!(near < far, "invalid depth range {}-{}", near, far);
+
+    let pixels = depth
+        .iter()
+        .map(|&d| (((d - near) / (far - near)).clamp(0.0, 1.0) * u16::MAX as f32).round() as u16)
+        .collect();
+    let image = ImageBuffer::<Luma<u16>, Vec<u16>>::from_raw(width, height, pixels).unwrap();
+
+    // the PNG encoder needs to seek, so encode into memory first
+    let mut encoded = Cursor::new(Vec::new());
+    DynamicImage::ImageLuma16(image).write_to(&mut encoded, ImageOutputFormat::Png)?;
+    writer.write_all(encoded.get_ref())?;
+    Ok(())
+}
+
+/// What the renderer draws for pixels whose ray misses the scene
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Background {
+    /// A vertical gradient, blended in linear light
+    Gradient { top: Color, bottom: Color },
+    /// Leaves misses transparent, e.g. for compositing
+    Transparent,
+}
+
+impl Background {
+    /// Returns the color of the background in the given image row, or `None` if it is transparent
+    pub fn color_at(&self, y: u32, height: u32) -> Option<Color> {
+        match *self {
+            Background::Gradient { top, bottom } => {
+                Some(top.lerp_linear(bottom, y as f32 / height as f32))
+            }
+            Background::Transparent => None,
+        }
+    }
+
+    /// Like `color_at`, but with an ordered dither depending on the pixel, so that the gradient shows no bands
+    pub fn dithered_color_at(&self, x: u32, y: u32, height: u32) -> Option<Color> {
+        match *self {
+            Background::Gradient { top, bottom } => {
+                Some(top.lerp_linear_dithered(bottom, y as f32 / height as f32, bayer_dither(x, y)))
+            }
+            Background::Transparent => None,
+        }
+    }
+}
+
+impl Default for Background {
+    /// A light blue sky
+    fn default() -> Background {
+        Background::Gradient {
+            top: Color::new(122, 170, 255),
+            bottom: Color::new(220, 220, 230),
+        }
+    }
+}
+
+impl Default for Raytracer {
+    fn default() -> Raytracer {
+        Raytracer::new(Scene::default())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RenderError {
+    /// The output buffer does not hold exactly one color per pixel
+    BufferSize { expected: usize, actual: usize },
+    /// The output has no alpha channel to represent a transparent background with
+    TransparentBackground,
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::BufferSize { expected, actual } => write!(
+                f,
+                "output buffer holds {} colors, but the image has {} pixels",
+                actual, expected
+            ),
+            RenderError::TransparentBackground => write!(
+                f,
+                "a transparent background needs an output with an alpha channel"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+pub trait Renderable: fmt::Debug {
+    fn intersect(&self, ray: &Ray) -> Option<f32>;
+
+    /// Returns all spans along the ray which lie inside the object, sorted by their entry distance
+    ///
+    /// Unlike `intersect`, this also reports roots behind the ray origin, which is needed to combine solids (see `Csg`)
+    fn intervals(&self, ray: &Ray) -> Vec<Interval>;
+
+    /// Returns whether the renderable encloses a volume, i.e. whether `intervals` describes it
+    ///
+    /// Only solids can be combined with `Csg`
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    /// Returns a point on the surface together with its outward normal, e.g. to place a light at the object
+    fn representative_point(&self) -> (Vec3, Vec3);
+
+    /// Returns a key derived from the content of the renderable, which is equal for renderables that compare equal
+    ///
+    /// Used to order scenes independently of insertion order (see `Scene::sort_by_content`)
+    fn content_key(&self) -> String;
+
+    /// Returns the renderable as `Any`, so that callers can get the concrete type back via `downcast_ref`
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// Builds a `Renderable::content_key` from the name of a type and its numeric fields
+fn content_key(name: &str, values: &[f32]) -> String {
+    let values: Vec<String> = values
+        .iter()
+        .map(|value| {
+            // `-0.0` and `0.0` compare equal, so they need the same key
+            let bits = if *value == 0.0 { 0 } else { value.to_bits() };
+            format!("{:08x}", bits)
+        })
+        .collect();
+    format!("{}({})", name, values.join(","))
+}
+
+/// A span along a ray, from the distance at which the ray enters a solid to the distance at which it exits again
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub enter: f32,
+    pub exit: f32,
+}
+
+impl Interval {
+    pub fn new(enter: f32, exit: f32) -> Interval {
+        debug_assert!(
+            enter <= exit,
+            "invalid interval: enters at {} but exits at {}",
+            enter,
+            exit
+        );
+        Interval { enter, exit }
+    }
+}
+
+/// Returns the closest surface in front of the ray origin, given the (sorted) intervals of a solid
+fn nearest_hit(intervals: &[Interval]) -> Option<f32> {
+    intervals.iter().find_map(|interval| {
+        if interval.enter >= 0.0 {
+            Some(interval.enter)
+        } else if interval.exit >= 0.0 && interval.exit.is_finite() {
+            // the ray origin lies inside the solid, so the closest surface is where it exits
+            // (unbounded solids like `HalfSpace` have no surface at infinity)
+            Some(interval.exit)
+        } else {
+            None
+        }
+    })
+}
+
+#[derive(Default, Debug)]
+pub struct Scene {
+    pub renderables: Vec<SceneObject>,
+}
+
+/// A renderable in a scene, together with the layer it has been added to
+#[derive(Debug)]
+pub struct SceneObject {
+    pub renderable: Box<dyn Renderable>,
+    pub layer: Option<String>,
+}
+
+impl Deref for SceneObject {
+    type Target = dyn Renderable;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.renderable
+    }
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene::default()
+    }
+
+    pub fn add<T: Renderable + 'static>(&mut self, renderable: T) {
+        self.renderables.push(SceneObject {
+            renderable: Box::new(renderable),
+            layer: None,
+        });
+    }
+
+    /// Adds a renderable tagged with a layer name, so it can be included or excluded with a `LayerFilter`
+    pub fn add_to_layer<T: Renderable + 'static>(&mut self, renderable: T, layer: &str) {
+        self.renderables.push(SceneObject {
+            renderable: Box::new(renderable),
+            layer: Some(layer.to_string()),
+        });
+    }
+
+    /// Sorts the renderables by their content, so logically identical scenes render identically regardless of insertion order
+    ///
+    /// This matters for coincident surfaces, where `intersect` prefers the lower index. The sort key is `Renderable::content_key`
+    pub fn sort_by_content(&mut self) {
+        // the layer is part of the key too, so that identical objects on different layers are ordered deterministically
+        self.renderables
+            .sort_by_cached_key(|object| (object.content_key(), object.layer.clone()));
+    }
+
+    /// Returns the layer of the renderable at the given index
+    pub fn layer(&self, index: usize) -> Option<&str> {
+        self.renderables.get(index)?.layer.as_deref()
+    }
+
+    /// Returns the index of the closest renderable hit by the ray, together with the distance at which it is hit
+    ///
+    /// If several renderables are hit at exactly the same distance, the one added first wins, so coincident surfaces resolve the same way every time
+    pub fn intersect(&self, ray: &Ray) -> Option<(usize, f32)> {
+        self.intersect_filtered(ray, None)
+    }
+
+    /// Like `intersect`, but treats every renderable rejected by the filter as absent
+    pub fn intersect_filtered(
+        &self,
+        ray: &Ray,
+        filter: Option<&LayerFilter>,
+    ) -> Option<(usize, f32)> {
+        let mut closest: Option<(usize, f32)> = None;
+        for (index, object) in self.renderables.iter().enumerate() {
+            if filter.is_some_and(|filter| !filter.accepts(object.layer.as_deref())) {
+                continue;
+            }
+
+            if let Some(t) = object.intersect(ray) {
+                debug_assert!(
+                    t >= 0.0,
+                    "invalid hit distance {} reported by {:?}",
+                    t,
+                    object.renderable
+                );
+                // strictly closer only, so that on a tie the lower index is kept
+                if closest.is_none_or(|(_, closest_t)| t < closest_t) {
+                    closest = Some((index, t));
+                }
+            }
+        }
+        closest
+    }
+}
+
+/// Selects which layers of a scene are rendered
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LayerFilter {
+    /// Only renders objects on one of these layers
+    Include(Vec<String>),
+    /// Renders everything except objects on one of these layers
+    Exclude(Vec<String>),
+}
+
+impl LayerFilter {
+    /// Returns whether an object on the given layer passes the filter (objects without a layer only pass exclusions)
+    pub fn accepts(&self, layer: Option<&str>) -> bool {
+        let listed =
+            |layers: &[String]| layer.is_some_and(|layer| layers.iter().any(|l| l == layer));
+        match self {
+            LayerFilter::Include(layers) => listed(layers),
+            LayerFilter::Exclude(layers) => !listed(layers),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl Sphere {
+    pub fn new(center: impl Into<Vec3>, radius: f32) -> Sphere {
+        Sphere {
+            center: center.into(),
+            radius,
+        }
+    }
+
+    /// Returns the distances `(t_enter, t_exit)` along the ray at which it enters and exits the sphere
+    ///
+    /// Both roots are reported, even if they lie behind the ray origin
+    // https://www.cs.princeton.edu/courses/archive/fall00/cs426/lectures/raycast/raycast.pdf (page 6-7)
+    pub fn intersect_interval(&self, ray: &Ray) -> Option<(f32, f32)> {
+        // distance vector between the center of the sphere and the ray origin
+        // https://www.scratchapixel.com/lessons/3d-basic-rendering/minimal-ray-tracer-rendering-simple-shapes/ray-sphere-intersection.html
+        let center_dir = self.center - ray.origin; // `L = O - P_0`
+
+        // project the distance vector onto the ray direction
+        // gives the distance from the viewpoint to the center of the sphere projected onto the ray
+        // (negative if the center of the sphere lies behind the ray origin)
+        let ray_to_center = center_dir.dot(ray.direction); // `t_ca = L . V`
+
+        // projects the center of the sphere onto the ray, so that `d` would be the (shortest) distance between the ray and the center of the sphere
+        // dot product of vector by itself is the square of it's magnitude (length)
+        let proj_dist2 = center_dir.dot(center_dir) - ray_to_center.powi(2); // `d^2 = L . L - t_ca^2`
+        let radius2 = self.radius.powi(2); // `r^2`
+
+        // if the projected distance is greater than the radius, the ray misses the sphere
+        if proj_dist2 > radius2 {
+            return None;
+        }
+
+        // the distance between the hit point and center of the sphere projected onto the ray
+        // (basically how deep the ray would have to penetrate the sphere to reach the center (halfway through))
+        let penetration_halfway = (radius2 - proj_dist2).sqrt(); // `t_hc = sqrt(r^2 - d^2)`
+
+        // both intersections: the ray enters the sphere at distance `t_0` and exits at distance `t_1`
+        let hit_in = ray_to_center - penetration_halfway; // `t_0 = t_ca - t_hc`
+        let hit_out = ray_to_center + penetration_halfway; // `t_1 = t_ca + t_hc`
+
+        Some((hit_in, hit_out))
+    }
+}
+
+impl Renderable for Sphere {
+    /// Returns the distance along the ray at which the sphere is intersected
+    fn intersect(&self, ray: &Ray) -> Option<f32> {
+        let (hit_in, hit_out) = self.intersect_interval(ray)?;
+
+        // if both `t0` and `t1` are negative, the ray is pointing away from the sphere (or the sphere is behind the camera/ray origin)
+        if hit_out < 0.0 {
+            return None;
+        }
+
+        // return the closest intersection in front of the ray origin
+        // (if only `t0` is negative, the ray origin lies inside the sphere)
+        Some(if hit_in >= 0.0 { hit_in } else { hit_out })
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        self.intersect_interval(ray)
+            .map(|(enter, exit)| vec![Interval::new(enter, exit)])
+            .unwrap_or_default()
+    }
+
+    /// Returns the top of the sphere
+    fn representative_point(&self) -> (Vec3, Vec3) {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        (self.center + up * self.radius, up)
+    }
+
+    fn content_key(&self) -> String {
+        let Sphere { center, radius } = *self;
+        content_key("Sphere", &[center.x, center.y, center.z, radius])
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// An axis-aligned box, spanning from the `min` to the `max` corner
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cuboid {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Cuboid {
+    pub fn new(min: impl Into<Vec3>, max: impl Into<Vec3>) -> Cuboid {
+        Cuboid {
+            min: min.into(),
+            max: max.into(),
+        }
+    }
+
+    /// Returns the span along the ray which lies inside the box, if any (a box is convex, so there is at most one)
+    // https://www.scratchapixel.com/lessons/3d-basic-rendering/minimal-ray-tracer-rendering-simple-shapes/ray-box-intersection.html
+    // https://tavianator.com/2011/ray_box.html
+    fn interval(&self, ray: &Ray) -> Option<Interval> {
+        // distances at which the ray crosses the planes of the three slabs
+        // (if the ray runs parallel to a slab, these are infinite, or NaN if the origin lies exactly on one of its planes)
+        let t_min = (self.min - ray.origin) * ray.inv_direction;
+        let t_max = (self.max - ray.origin) * ray.inv_direction;
+
+        // the ray is inside the box when it is in between the planes of all three slabs
+        // (clamping to the running `enter`/`exit` first makes `min`/`max` ignore the NaN cases)
+        let mut enter = f32::NEG_INFINITY;
+        let mut exit = f32::INFINITY;
+        for (t0, t1) in [(t_min.x, t_max.x), (t_min.y, t_max.y), (t_min.z, t_max.z)] {
+            enter = enter.max(t0.max(enter).min(t1.max(enter)));
+            exit = exit.min(t0.min(exit).max(t1.min(exit)));
+        }
+
+        if enter > exit {
+            return None;
+        }
+
+        Some(Interval::new(enter, exit))
+    }
+}
+
+impl Renderable for Cuboid {
+    fn intersect(&self, ray: &Ray) -> Option<f32> {
+        self.interval(ray)
+            .and_then(|interval| nearest_hit(&[interval]))
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        self.interval(ray).into_iter().collect()
+    }
+
+    /// Returns the center of the top face
+    fn representative_point(&self) -> (Vec3, Vec3) {
+        let center = (self.min + self.max) / 2.0;
+        (
+            Vec3::new(center.x, self.max.y, center.z),
+            Vec3::new(0.0, 1.0, 0.0),
+        )
+    }
+
+    fn content_key(&self) -> String {
+        let Cuboid { min, max } = *self;
+        content_key("Cuboid", &[min.x, min.y, min.z, max.x, max.y, max.z])
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A set of points, each rendered as a small disk (splat) that always faces the ray
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointCloud {
+    pub points: Vec<Vec3>,
+    /// Radius of the splat drawn for every point
+    pub radius: f32,
+}
+
+impl PointCloud {
+    pub fn new(points: Vec<Vec3>, radius: f32) -> PointCloud {
+        PointCloud { points, radius }
+    }
+}
+
+impl Renderable for PointCloud {
+    fn intersect(&self, ray: &Ray) -> Option<f32> {
+        let radius2 = self.radius.powi(2);
+
+        let mut closest: Option<f32> = None;
+        for point in &self.points {
+            // the splat lies in the plane through the point perpendicular to the ray, so the ray hits it at the projected distance
+            let point_dir = *point - ray.origin;
+            let t = point_dir.dot(ray.direction);
+            if t < 0.0 {
+                continue;
+            }
+
+            // squared distance between the hit point and the center of the splat
+            let dist2 = point_dir.dot(point_dir) - t.powi(2);
+            if dist2 <= radius2 && closest.is_none_or(|closest_t| t < closest_t) {
+                closest = Some(t);
+            }
+        }
+        closest
+    }
+
+    /// Splats are flat and enclose no volume, so there are no intervals
+    fn intervals(&self, _ray: &Ray) -> Vec<Interval> {
+        Vec::new()
+    }
+
+    fn is_solid(&self) -> bool {
+        false
+    }
+
+    /// Returns the point closest to the centroid of the cloud
+    ///
+    /// Splats always face the ray, so they have no fixed normal; up is reported instead
+    fn representative_point(&self) -> (Vec3, Vec3) {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        if self.points.is_empty() {
+            return (Vec3::zero(), up);
+        }
+
+        let centroid = self
+            .points
+            .iter()
+            .fold(Vec3::zero(), |sum, point| sum + *point)
+            / self.points.len() as f32;
+        let closest = self
+            .points
+            .iter()
+            .min_by(|a, b| {
+                (**a - centroid)
+                    .sqr_magnitude()
+                    .total_cmp(&(**b - centroid).sqr_magnitude())
+            })
+            .unwrap();
+        (*closest, up)
+    }
+
+    fn content_key(&self) -> String {
+        let mut values = vec![self.radius];
+        values.extend(self.points.iter().flat_map(|p| [p.x, p.y, p.z]));
+        content_key("PointCloud", &values)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Normalizes the normal of a plane, which has to have a direction to begin with
+fn plane_normal(normal: Vec3) -> Vec3 {
+    // rejects zero as well as NaN and infinite components, which would end up in every interval
+    if !normal.sqr_magnitude().is_normal() {
+        panic!("plane normal {} has no direction", normal);
+    }
+    normal.normalized()
+}
+
+/// Everything on the back side of a plane through `point`, i.e. opposite to where the `normal` points
+///
+/// Mostly useful as a `Csg` operand, e.g. to clip a solid to a cutaway
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HalfSpace {
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+impl HalfSpace {
+    pub fn new(point: impl Into<Vec3>, normal: impl Into<Vec3>) -> HalfSpace {
+        HalfSpace {
+            point: point.into(),
+            normal: plane_normal(normal.into()),
+        }
+    }
+
+    /// Returns the part of the ray behind the plane, if any
+    fn interval(&self, ray: &Ray) -> Option<Interval> {
+        // signed distance of the ray origin from the plane (positive on the outside)
+        let distance = (ray.origin - self.point).dot(self.normal);
+        // how fast the ray moves away from the plane
+        let speed = ray.direction.dot(self.normal);
+
+        if speed == 0.0 {
+            // the ray runs parallel to the plane, so it is either inside for its whole length or never
+            return if distance <= 0.0 {
+                Some(Interval::new(f32::NEG_INFINITY, f32::INFINITY))
+            } else {
+                None
+            };
+        }
+
+        let t = -distance / speed;
+        if speed > 0.0 {
+            Some(Interval::new(f32::NEG_INFINITY, t))
+        } else {
+            Some(Interval::new(t, f32::INFINITY))
+        }
+    }
+}
+
+impl Renderable for HalfSpace {
+    fn intersect(&self, ray: &Ray) -> Option<f32> {
+        self.interval(ray)
+            .and_then(|interval| nearest_hit(&[interval]))
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        self.interval(ray).into_iter().collect()
+    }
+
+    /// Returns the anchor point of the plane
+    fn representative_point(&self) -> (Vec3, Vec3) {
+        (self.point, self.normal)
+    }
+
+    fn content_key(&self) -> String {
+        let HalfSpace { point, normal } = *self;
+        content_key(
+            "HalfSpace",
+            &[point.x, point.y, point.z, normal.x, normal.y, normal.z],
+        )
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Everything in between two parallel planes: the one through `point` and the one `thickness` further along the `normal`
+///
+/// Mostly useful as a `Csg` operand, e.g. to cut a slice out of a solid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Slab {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub thickness: f32,
+}
+
+impl Slab {
+    pub fn new(point: impl Into<Vec3>, normal: impl Into<Vec3>, thickness: f32) -> Slab {
+        // the intervals assume the far plane lies along the normal, which a negative thickness would flip
+        if thickness.is_nan() || thickness < 0.0 {
+            panic!("slab thickness {} is not a distance", thickness);
+        }
+        Slab {
+            point: point.into(),
+            normal: plane_normal(normal.into()),
+            thickness,
+        }
+    }
+
+    /// Returns the part of the ray in between the two planes, if any
+    fn interval(&self, ray: &Ray) -> Option<Interval> {
+        // signed distance of the ray origin from the first plane, and how fast the ray moves along the normal
+        let distance = (ray.origin - self.point).dot(self.normal);
+        let speed = ray.direction.dot(self.normal);
+
+        if speed == 0.0 {
+            return if (0.0..=self.thickness).contains(&distance) {
+                Some(Interval::new(f32::NEG_INFINITY, f32::INFINITY))
+            } else {
+                None
+            };
+        }
+
+        // distances at which the ray crosses the two planes
+        let t_near = -distance / speed;
+        let t_far = (self.thickness - distance) / speed;
+        Some(Interval::new(t_near.min(t_far), t_near.max(t_far)))
+    }
+}
+
+impl Renderable for Slab {
+    fn intersect(&self, ray: &Ray) -> Option<f32> {
+        self.interval(ray)
+            .and_then(|interval| nearest_hit(&[interval]))
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        self.interval(ray).into_iter().collect()
+    }
+
+    /// Returns the point on the far plane above the anchor point
+    fn representative_point(&self) -> (Vec3, Vec3) {
+        (self.point + self.normal * self.thickness, self.normal)
+    }
+
+    fn content_key(&self) -> String {
+        let Slab {
+            point,
+            normal,
+            thickness,
+        } = *self;
+        content_key(
+            "Slab",
+            &[
+                point.x, point.y, point.z, normal.x, normal.y, normal.z, thickness,
+            ],
+        )
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// The boolean operation used to combine the two operands of a `Csg`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CsgOp {
+    /// Everything inside of either operand
+    Union,
+    /// Everything inside of both operands
+    Intersection,
+    /// Everything inside of the left operand, but not inside the right one
+    Difference,
+}
+
+impl CsgOp {
+    fn contains(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOp::Union => in_left || in_right,
+            CsgOp::Intersection => in_left && in_right,
+            CsgOp::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// Constructive solid geometry: a boolean combination of two solids
+///
+/// Both operands have to be solids (see `Renderable::is_solid`), since only their intervals are combined.
+/// A `PointCloud` operand would silently vanish, so `new` rejects it
+#[derive(Debug)]
+pub struct Csg {
+    // private, so that every `Csg` goes through the check in `new`
+    op: CsgOp,
+    left: Box<dyn Renderable>,
+    right: Box<dyn Renderable>,
+}
+
+impl Csg {
+    pub fn new<L: Renderable + 'static, R: Renderable + 'static>(
+        op: CsgOp,
+        left: L,
+        right: R,
+    ) -> Csg {
+        if !left.is_solid() || !right.is_solid() {
+            panic!("csg operands have to be solids");
+        }
+        Csg {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn op(&self) -> CsgOp {
+        self.op
+    }
+
+    pub fn left(&self) -> &dyn Renderable {
+        self.left.as_ref()
+    }
+
+    pub fn right(&self) -> &dyn Renderable {
+        self.right.as_ref()
+    }
+}
+
+impl Renderable for Csg {
+    fn intersect(&self, ray: &Ray) -> Option<f32> {
+        nearest_hit(&self.intervals(ray))
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        // every interval boundary of both operands, tagged with the operand it belongs to and whether the ray enters or exits there
+        let mut events = Vec::new();
+        for interval in self.left.intervals(ray) {
+            events.push((interval.enter, true, true));
+            events.push((interval.exit, true, false));
+        }
+        for interval in self.right.intervals(ray) {
+            events.push((interval.enter, false, true));
+            events.push((interval.exit, false, false));
+        }
+        // at equal distances, entries go first, so that operands sharing a face don't leave a seam in between
+        events.sort_by(|a, b| a.0.total_cmp(&b.0).then(b.2.cmp(&a.2)));
+
+        // sweep along the ray and record where the combined solid starts and ends
+        let mut intervals = Vec::new();
+        let (mut in_left, mut in_right) = (false, false);
+        let mut enter = 0.0;
+        for (t, is_left, entering) in events {
+            let was_inside = self.op.contains(in_left, in_right);
+            if is_left {
+                in_left = entering;
+            } else {
+                in_right = entering;
+            }
+            let is_inside = self.op.contains(in_left, in_right);
+
+            if !was_inside && is_inside {
+                enter = t;
+            } else if was_inside && !is_inside && t > enter {
+                intervals.push(Interval::new(enter, t));
+            }
+        }
+
+        intervals
+    }
+
+    /// Returns the surface point closest to the representative point of either operand
+    ///
+    /// Each operand's point is probed along its normal, in both directions, and the nearest boundary of the combined solid wins.
+    /// The normal is the probe direction, which is exact for flat and spherical operands and approximate otherwise.
+    /// If no probe hits the combined surface (e.g. for an empty intersection), the left operand's point is returned as is
+    fn representative_point(&self) -> (Vec3, Vec3) {
+        let fallback = self.left.representative_point();
+
+        let mut closest: Option<(f32, Vec3, Vec3)> = None;
+        for (point, normal) in [fallback, self.right.representative_point()] {
+            // points inward, so the ray enters the combined solid at a boundary in front of it and exits at one behind
+            let ray = Ray::new(point, -normal.normalized());
+            for interval in self.intervals(&ray) {
+                for (t, outward) in [(interval.enter, normal), (interval.exit, -normal)] {
+                    if t.is_finite() && closest.is_none_or(|(closest_t, _, _)| t.abs() < closest_t)
+                    {
+                        closest = Some((t.abs(), ray.at(t), outward));
+                    }
+                }
+            }
+        }
+
+        closest.map_or(fallback, |(_, point, normal)| (point, normal.normalized()))
+    }
+
+    fn content_key(&self) -> String {
+        let op = match self.op {
+            CsgOp::Union => "Union",
+            CsgOp::Intersection => "Intersection",
+            CsgOp::Difference => "Difference",
+        };
+        format!(
+            "Csg({},{},{})",
+            op,
+            self.left.content_key(),
+            self.right.content_key()
+        )
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    origin: Vec3,
+    direction: Vec3,
+    /// Component-wise inverse of `direction`, precomputed once so box tests don't have to divide
+    inv_direction: Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: impl Into<Vec3>, direction: impl Into<Vec3>) -> Ray {
+        let direction = direction.into();
+        debug_assert!(
+            (direction.sqr_magnitude() - 1.0).abs() < 1e-4,
+            "ray direction {} is not normalized",
+            direction
+        );
+        Ray {
+            origin: origin.into(),
+            direction,
+            inv_direction: 1.0 / direction,
+        }
+    }
+
+    pub fn origin(&self) -> Vec3 {
+        self.origin
+    }
+
+    pub fn direction(&self) -> Vec3 {
+        self.direction
+    }
+
+    /// Returns the point at distance `t` along the ray
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+    use std::panic::AssertUnwindSafe;
+
+    /// A ray starting on the image plane at (`x`, `y`), pointing into the scene like the primary rays do
+    fn forward_ray(x: f32, y: f32) -> Ray {
+        Ray::new([x, y, 0.0], [0.0, 0.0, 1.0])
+    }
+
+    #[test]
+    fn csg_difference_carves_hole_through_sphere() {
+        let sphere = Sphere::new([0.0, 0.0, 10.0], 5.0);
+        let drill = Cuboid::new([-1.0, -1.0, 0.0], [1.0, 1.0, 20.0]);
+        let csg = Csg::new(CsgOp::Difference, sphere, drill);
+
+        // straight through the hole
+        assert_eq!(csg.intersect(&forward_ray(0.0, 0.0)), None);
+        // next to the hole, the sphere is still there
+        assert_eq!(csg.intersect(&forward_ray(3.0, 0.0)), Some(6.0));
+
+        // across the hole, the ray passes through both walls of the sphere
+        let across = Ray::new([-10.0, 0.0, 10.0], [1.0, 0.0, 0.0]);
+        assert_eq!(
+            csg.intervals(&across),
+            vec![Interval::new(5.0, 9.0), Interval::new(11.0, 15.0)]
+        );
+    }
+
+    #[test]
+    fn csg_intersection_keeps_overlap() {
+        let sphere = Sphere::new([0.0, 0.0, 10.0], 5.0);
+        let cuboid = Cuboid::new([-4.0, -4.0, 8.0], [4.0, 4.0, 12.0]);
+        let csg = Csg::new(CsgOp::Intersection, sphere, cuboid);
+
+        assert_eq!(
+            csg.intervals(&forward_ray(0.0, 0.0)),
+            vec![Interval::new(8.0, 12.0)]
+        );
+        assert_eq!(csg.intersect(&forward_ray(3.0, 0.0)), Some(8.0));
+        // inside the sphere, but outside the cuboid
+        assert_eq!(csg.intersect(&forward_ray(4.5, 0.0)), None);
+    }
+
+    #[test]
+    fn csg_union_merges_overlapping_intervals() {
+        let sphere = Sphere::new([0.0, 0.0, 10.0], 5.0);
+        let cuboid = Cuboid::new([-1.0, -1.0, 12.0], [7.0, 1.0, 20.0]);
+        let csg = Csg::new(CsgOp::Union, sphere, cuboid);
+
+        assert_eq!(
+            csg.intervals(&forward_ray(0.0, 0.0)),
+            vec![Interval::new(5.0, 20.0)]
+        );
+        // only the cuboid sticks out here
+        assert_eq!(csg.intersect(&forward_ray(6.0, 0.0)), Some(12.0));
+        // and neither operand is here
+        assert_eq!(csg.intersect(&forward_ray(0.0, 6.0)), None);
+    }
+
+    #[test]
+    fn sphere_interval_reports_both_roots() {
+        let sphere = Sphere::new([0.0, 0.0, 10.0], 5.0);
+        assert_eq!(
+            sphere.intersect_interval(&forward_ray(0.0, 0.0)),
+            Some((5.0, 15.0))
+        );
+        assert_eq!(
+            sphere.intersect_interval(&forward_ray(3.0, 0.0)),
+            Some((6.0, 14.0))
+        );
+        assert_eq!(sphere.intersect_interval(&forward_ray(6.0, 0.0)), None);
+
+        // roots behind the ray origin are reported too
+        let behind = Ray::new([0.0, 0.0, 20.0], [0.0, 0.0, 1.0]);
+        assert_eq!(sphere.intersect_interval(&behind), Some((-15.0, -5.0)));
+        assert_eq!(sphere.intersect(&behind), None);
+    }
+
+    #[test]
+    fn sphere_hit_from_inside_is_exit() {
+        let sphere = Sphere::new([0.0, 0.0, 10.0], 5.0);
+        let inside = Ray::new([0.0, 0.0, 8.0], [0.0, 0.0, 1.0]);
+
+        assert_eq!(sphere.intersect_interval(&inside), Some((-3.0, 7.0)));
+        assert_eq!(sphere.intersect(&inside), Some(7.0));
+    }
+
+    #[test]
+    fn scene_intersect_prefers_lower_index_on_tie() {
+        let mut scene = Scene::new();
+        scene.add(Sphere::new([0.0, 0.0, 10.0], 5.0));
+        scene.add(Sphere::new([0.0, 0.0, 10.0], 5.0));
+        assert_eq!(scene.intersect(&forward_ray(0.0, 0.0)), Some((0, 5.0)));
+
+        // a closer object still wins, regardless of its index
+        scene.add(Sphere::new([0.0, 0.0, 8.0], 5.0));
+        assert_eq!(scene.intersect(&forward_ray(0.0, 0.0)), Some((2, 3.0)));
+    }
+
+    /// The straightforward slab test, dividing by the ray direction and handling parallel rays explicitly
+    fn naive_cuboid_interval(cuboid: &Cuboid, ray: &Ray) -> Option<(f32, f32)> {
+        let (origin, direction) = (ray.origin(), ray.direction());
+        let axes = [
+            (origin.x, direction.x, cuboid.min.x, cuboid.max.x),
+            (origin.y, direction.y, cuboid.min.y, cuboid.max.y),
+            (origin.z, direction.z, cuboid.min.z, cuboid.max.z),
+        ];
+
+        let mut enter = f32::NEG_INFINITY;
+        let mut exit = f32::INFINITY;
+        for (origin, direction, min, max) in axes {
+            if direction == 0.0 {
+                if origin < min || origin > max {
+                    return None;
+                }
+                continue;
+            }
+
+            let t_min = (min - origin) / direction;
+            let t_max = (max - origin) / direction;
+            enter = enter.max(t_min.min(t_max));
+            exit = exit.min(t_min.max(t_max));
+        }
+
+        (enter <= exit).then_some((enter, exit))
+    }
+
+    fn cuboid_interval(cuboid: &Cuboid, ray: &Ray) -> Option<(f32, f32)> {
+        let intervals = cuboid.intervals(ray);
+        assert!(intervals.len() <= 1);
+        intervals.first().map(|i| (i.enter, i.exit))
+    }
+
+    fn assert_matches_naive_slab_test(cuboid: &Cuboid, ray: &Ray) {
+        match (
+            cuboid_interval(cuboid, ray),
+            naive_cuboid_interval(cuboid, ray),
+        ) {
+            (Some((enter, exit)), Some((naive_enter, naive_exit))) => {
+                // multiplying by the inverse rounds slightly differently than dividing
+                assert!((enter - naive_enter).abs() < 1e-3, "{:?}", ray);
+                assert!((exit - naive_exit).abs() < 1e-3, "{:?}", ray);
+            }
+            (None, None) => {}
+            (fast, naive) => panic!("{:?}: {:?} != {:?}", ray, fast, naive),
+        }
+    }
+
+    #[test]
+    fn cuboid_matches_naive_slab_test_on_parallel_rays() {
+        let cuboid = Cuboid::new([-1.0, -1.0, -1.0], [1.0, 1.0, 1.0]);
+        let directions = [
+            [1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, -1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, -1.0],
+            // only parallel to one of the slabs
+            [0.6, 0.8, 0.0],
+            [0.0, -0.6, 0.8],
+            [-0.8, 0.0, -0.6],
+        ];
+
+        // origins inside, outside and exactly on the planes of the slabs, so the inverse direction produces NaNs
+        let coordinates = [-3.0, -1.0, -0.5, 0.0, 0.5, 1.0, 3.0];
+        for x in coordinates {
+            for y in coordinates {
+                for z in coordinates {
+                    for direction in directions {
+                        assert_matches_naive_slab_test(&cuboid, &Ray::new([x, y, z], direction));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cuboid_matches_naive_slab_test_on_random_rays() {
+        // xorshift, so the rays are the same on every run
+        let mut state = 0x2545_f491_u32;
+        let mut random = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f32 / u32::MAX as f32 * 2.0 - 1.0
+        };
+
+        let cuboid = Cuboid::new([-1.0, -2.0, 0.5], [2.0, 1.0, 3.0]);
+        for _ in 0..10_000 {
+            let origin = Vec3::new(random(), random(), random()) * 5.0;
+            let direction = Vec3::new(random(), random(), random()).normalized();
+            assert_matches_naive_slab_test(&cuboid, &Ray::new(origin, direction));
+        }
+    }
+
+    #[test]
+    fn render_and_encode_roundtrips_through_memory() {
+        let mut scene = Scene::new();
+        scene.add(Sphere::new([10.0, 10.0, 20.0], 5.0));
+        let raytracer = Raytracer::new(scene);
+
+        let mut encoded = Vec::new();
+        raytracer
+            .render_and_encode((20, 20), ImageOutputFormat::Png, &mut encoded)
+            .unwrap();
+
+        let decoded = image::load_from_memory(&encoded).unwrap().to_rgba8();
+        assert_eq!(decoded.dimensions(), (20, 20));
+        assert_eq!(*decoded.get_pixel(10, 10), Rgba([0, 255, 0, 255]));
+        let background = Background::default().color_at(0, 20).unwrap();
+        assert_eq!(*decoded.get_pixel(0, 0), background.to_rgba());
+    }
+
+    #[test]
+    fn constructors_accept_plain_arrays() {
+        let sphere = Sphere::new([1.0, 2.0, 3.0], 4.0);
+        assert_eq!(sphere, Sphere::new(Vec3::new(1.0, 2.0, 3.0), 4.0));
+
+        let ray = Ray::new([1.0, 2.0, 10.0], [0.0, 0.0, -1.0]);
+        assert_eq!(ray.origin(), Vec3::new(1.0, 2.0, 10.0));
+        assert_eq!(sphere.intersect(&ray), Some(3.0));
+    }
+
+    #[test]
+    fn sphere_intersected_with_half_space_is_hemisphere() {
+        let sphere = Sphere::new([0.0, 0.0, 10.0], 5.0);
+        // keeps everything above y = 0
+        let upper = HalfSpace::new([0.0, 0.0, 0.0], [0.0, -1.0, 0.0]);
+        let hemisphere = Csg::new(CsgOp::Intersection, sphere, upper);
+
+        assert!(hemisphere.intersect(&forward_ray(0.0, 2.0)).is_some());
+        assert_eq!(hemisphere.intersect(&forward_ray(0.0, -2.0)), None);
+
+        // from above, the ray hits the dome; from below, the flat cut face
+        let down = Ray::new([0.0, 10.0, 10.0], [0.0, -1.0, 0.0]);
+        assert_eq!(hemisphere.intersect(&down), Some(5.0));
+        let up = Ray::new([0.0, -10.0, 10.0], [0.0, 1.0, 0.0]);
+        assert_eq!(hemisphere.intersect(&up), Some(10.0));
+    }
+
+    #[test]
+    fn slab_cuts_slice_out_of_sphere() {
+        let sphere = Sphere::new([0.0, 0.0, 10.0], 5.0);
+        let slice = Slab::new([0.0, -1.0, 0.0], [0.0, 1.0, 0.0], 2.0);
+        let csg = Csg::new(CsgOp::Difference, sphere, slice);
+
+        let down = Ray::new([0.0, 10.0, 10.0], [0.0, -1.0, 0.0]);
+        assert_eq!(
+            csg.intervals(&down),
+            vec![Interval::new(5.0, 9.0), Interval::new(11.0, 15.0)]
+        );
+        assert_eq!(csg.intersect(&forward_ray(0.0, 0.0)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "has no direction")]
+    fn half_space_rejects_zero_normal() {
+        HalfSpace::new([0.0, 0.0, 0.0], [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "has no direction")]
+    fn slab_rejects_zero_normal() {
+        Slab::new([0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a distance")]
+    fn slab_rejects_negative_thickness() {
+        Slab::new([0.0, 0.0, 0.0], [0.0, 1.0, 0.0], -2.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a distance")]
+    fn slab_rejects_nan_thickness() {
+        Slab::new([0.0, 0.0, 0.0], [0.0, 1.0, 0.0], f32::NAN);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "is not normalized")]
+    fn ray_rejects_unnormalized_direction() {
+        Ray::new([0.0, 0.0, 0.0], [0.0, 0.0, 2.0]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "invalid hit distance")]
+    fn scene_rejects_nan_hit_from_degenerate_sphere() {
+        let mut scene = Scene::new();
+        scene.add(Sphere::new([f32::NAN, 0.0, 10.0], 5.0));
+        scene.intersect(&forward_ray(0.0, 0.0));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "invalid interval")]
+    fn interval_rejects_exit_before_enter() {
+        Interval::new(2.0, 1.0);
+    }
+
+    /// Returns whether the pixel at (`x`, `y`) has been drawn onto a canvas with a transparent background
+    fn is_drawn(canvas: &Canvas, x: u32, y: u32) -> bool {
+        canvas.image.get_pixel(x, y)[3] != 0
+    }
+
+    #[test]
+    fn layer_filter_renders_only_selected_layers() {
+        let mut scene = Scene::new();
+        scene.add_to_layer(Sphere::new([10.0, 10.0, 20.0], 5.0), "foreground");
+        scene.add_to_layer(Sphere::new([30.0, 10.0, 20.0], 5.0), "background");
+        scene.add(Sphere::new([50.0, 10.0, 20.0], 5.0));
+        assert_eq!(scene.layer(0), Some("foreground"));
+        assert_eq!(scene.layer(2), None);
+
+        let mut raytracer = Raytracer::new(scene);
+        raytracer.background = Background::Transparent;
+        let render = |raytracer: &Raytracer| {
+            let mut canvas = Canvas::new(60, 20);
+            raytracer.render(&mut canvas);
+            [10, 30, 50].map(|x| is_drawn(&canvas, x, 10))
+        };
+
+        assert_eq!(render(&raytracer), [true, true, true]);
+
+        raytracer.layer_filter = Some(LayerFilter::Include(vec!["foreground".to_string()]));
+        assert_eq!(render(&raytracer), [true, false, false]);
+
+        raytracer.layer_filter = Some(LayerFilter::Exclude(vec!["foreground".to_string()]));
+        assert_eq!(render(&raytracer), [false, true, true]);
+    }
+
+    #[test]
+    fn render_into_matches_render() {
+        let mut scene = Scene::new();
+        scene.add(Sphere::new([10.0, 8.0, 20.0], 6.0));
+        scene.add(Cuboid::new([20.0, 2.0, 10.0], [28.0, 12.0, 30.0]));
+        let raytracer = Raytracer::new(scene);
+        let (width, height) = (32, 16);
+
+        let mut canvas = Canvas::new(width, height);
+        raytracer.render(&mut canvas);
+
+        let mut buffer = vec![Color::new(0, 0, 0); (width * height) as usize];
+        raytracer.render_into((width, height), &mut buffer).unwrap();
+
+        for y in 0..height {
+            for x in 0..width {
+                let color = buffer[(y * width + x) as usize];
+                assert_eq!(
+                    canvas.image.get_pixel(x, y),
+                    color.to_rgba(),
+                    "({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn render_into_rejects_wrong_buffer_size() {
+        let raytracer = Raytracer::default();
+        let mut buffer = vec![Color::new(0, 0, 0); 10];
+        assert_eq!(
+            raytracer.render_into((4, 4), &mut buffer),
+            Err(RenderError::BufferSize {
+                expected: 16,
+                actual: 10
+            })
+        );
+    }
+
+    #[test]
+    fn transparent_background_leaves_misses_transparent() {
+        let mut scene = Scene::new();
+        scene.add(Sphere::new([10.0, 10.0, 20.0], 5.0));
+        let mut raytracer = Raytracer::new(scene);
+        raytracer.background = Background::Transparent;
+
+        let mut canvas = Canvas::new(20, 20);
+        raytracer.render(&mut canvas);
+        assert_eq!(canvas.image.get_pixel(10, 10)[3], 255);
+        assert_eq!(canvas.image.get_pixel(0, 0)[3], 0);
+
+        // the gradient is opaque everywhere
+        raytracer.background = Background::default();
+        raytracer.render(&mut canvas);
+        assert_eq!(canvas.image.get_pixel(0, 0)[3], 255);
+    }
+
+    #[test]
+    fn render_into_rejects_transparent_background() {
+        let raytracer = Raytracer {
+            background: Background::Transparent,
+            ..Raytracer::default()
+        };
+        let mut buffer = vec![Color::new(0, 0, 0); 16];
+        assert_eq!(
+            raytracer.render_into((4, 4), &mut buffer),
+            Err(RenderError::TransparentBackground)
+        );
+    }
+
+    #[test]
+    fn pixel_aspect_squashes_circle_horizontally() {
+        let mut scene = Scene::new();
+        scene.add(Sphere::new([100.0, 50.0, 100.0], 40.0));
+        let mut raytracer = Raytracer::new(scene);
+        raytracer.background = Background::Transparent;
+
+        // returns the width and height of the rendered circle in pixels
+        let extent = |raytracer: &Raytracer, center_x: u32| {
+            let mut canvas = Canvas::new(200, 100);
+            raytracer.render(&mut canvas);
+            let width = (0..200).filter(|&x| is_drawn(&canvas, x, 50)).count();
+            let height = (0..100).filter(|&y| is_drawn(&canvas, center_x, y)).count();
+            (width, height)
+        };
+
+        // a diameter of 80 units, including both boundary pixels
+        assert_eq!(extent(&raytracer, 100), (81, 81));
+
+        // every pixel now covers two units horizontally, so the circle is half as wide
+        raytracer.pixel_aspect = 2.0;
+        assert_eq!(extent(&raytracer, 50), (41, 81));
+    }
+
+    #[test]
+    fn sorted_scenes_render_identically_regardless_of_insertion_order() {
+        // coincident surfaces, where insertion order would otherwise decide which object wins the tie
+        let objects = || -> Vec<Box<dyn Renderable>> {
+            vec![
+                Box::new(Sphere::new([10.0, 10.0, 20.0], 5.0)),
+                Box::new(Cuboid::new([5.0, 5.0, 15.0], [15.0, 15.0, 30.0])),
+                Box::new(Sphere::new([30.0, 10.0, 20.0], 6.0)),
+                Box::new(Csg::new(
+                    CsgOp::Union,
+                    Sphere::new([30.0, 10.0, 20.0], 6.0),
+                    Cuboid::new([24.0, 4.0, 14.0], [28.0, 16.0, 26.0]),
+                )),
+            ]
+        };
+        let scene_in_order = |order: [usize; 4]| {
+            let mut objects: Vec<_> = objects().into_iter().map(Some).collect();
+            let mut scene = Scene::new();
+            for index in order {
+                scene.renderables.push(SceneObject {
+                    renderable: objects[index].take().unwrap(),
+                    layer: None,
+                });
+            }
+            scene.sort_by_content();
+            scene
+        };
+
+        let a = Raytracer::new(scene_in_order([0, 1, 2, 3]));
+        let b = Raytracer::new(scene_in_order([3, 1, 0, 2]));
+        for y in 0..20 {
+            for x in 0..40 {
+                let ray = forward_ray(x as f32, y as f32);
+                let hit_key = |raytracer: &Raytracer| {
+                    raytracer
+                        .scene
+                        .intersect(&ray)
+                        .map(|(index, t)| (raytracer.scene.renderables[index].content_key(), t))
+                };
+                assert_eq!(hit_key(&a), hit_key(&b), "({}, {})", x, y);
+            }
+        }
+
+        let render = |raytracer: &Raytracer| {
+            let mut encoded = Vec::new();
+            raytracer
+                .render_and_encode((40, 20), ImageOutputFormat::Png, &mut encoded)
+                .unwrap();
+            encoded
+        };
+        assert_eq!(render(&a), render(&b));
+    }
+
+    #[test]
+    fn content_key_treats_negative_zero_as_zero() {
+        let positive = Sphere::new([0.0, 1.0, 2.0], 3.0);
+        let negative = Sphere::new([-0.0, 1.0, 2.0], 3.0);
+        assert_eq!(positive, negative);
+        assert_eq!(positive.content_key(), negative.content_key());
+        assert_ne!(
+            positive.content_key(),
+            Sphere::new([0.0, 1.0, 2.0], 4.0).content_key()
+        );
+    }
+
+    #[test]
+    fn sphere_representative_point_lies_on_surface() {
+        let sphere = Sphere::new([3.0, -2.0, 40.0], 7.5);
+        let (point, normal) = sphere.representative_point();
+        assert!(((point - sphere.center).magnitude() - sphere.radius).abs() < 1e-4);
+        assert!((normal - (point - sphere.center) / sphere.radius).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn csg_representative_point_lies_on_combined_surface() {
+        let sphere = Sphere::new([0.0, 0.0, 50.0], 10.0);
+        // cuts off the top of the sphere, which is where the sphere's own representative point is
+        let cut = Csg::new(
+            CsgOp::Difference,
+            sphere,
+            HalfSpace::new([0.0, 5.0, 50.0], [0.0, -1.0, 0.0]),
+        );
+        let (point, normal) = cut.representative_point();
+        assert!((point - Vec3::new(0.0, 5.0, 50.0)).magnitude() < 1e-4);
+        assert!((normal - Vec3::new(0.0, 1.0, 0.0)).magnitude() < 1e-4);
+
+        // a ray towards the point from outside hits the combined solid right there
+        let ray = Ray::new(point + normal * 20.0, -normal);
+        assert!((cut.intersect(&ray).unwrap() - 20.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn point_cloud_splat_is_hit_within_radius() {
+        let cloud = PointCloud::new(vec![Vec3::new(0.0, 0.0, 10.0)], 2.0);
+        assert_eq!(cloud.intersect(&forward_ray(1.99, 0.0)), Some(10.0));
+        assert_eq!(cloud.intersect(&forward_ray(0.0, -1.99)), Some(10.0));
+        assert_eq!(cloud.intersect(&forward_ray(2.01, 0.0)), None);
+        assert_eq!(cloud.intersect(&forward_ray(1.5, 1.5)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "csg operands have to be solids")]
+    fn csg_rejects_point_cloud_operand() {
+        let cloud = PointCloud::new(vec![Vec3::new(0.0, 0.0, 10.0)], 2.0);
+        Csg::new(CsgOp::Union, cloud, Sphere::new([0.0, 0.0, 30.0], 5.0));
+    }
+
+    #[test]
+    fn contact_sheet_arranges_cells_in_grid() {
+        let mut canvases = vec![
+            Canvas::new(10, 8),
+            Canvas::new(6, 4),
+            Canvas::new(7, 8),
+            Canvas::new(10, 3),
+        ];
+        canvases[2].draw(1, 2, Color::new(255, 0, 0));
+
+        let sheet = Canvas::contact_sheet(&canvases, 2);
+        assert_eq!(sheet.get_dimensions(), (20, 16));
+        // the third canvas starts the second row
+        assert!(is_drawn(&sheet, 1, 10));
+        assert!(!is_drawn(&sheet, 11, 2));
+
+        // fewer canvases than columns don't leave empty columns
+        assert_eq!(
+            Canvas::contact_sheet(&canvases[..3], 5).get_dimensions(),
+            (30, 8)
+        );
+    }
+
+    #[test]
+    fn lerp_linear_midpoint_is_brighter_than_lerp() {
+        let red = Color::new(255, 0, 0);
+        let green = Color::new(0, 255, 0);
+        assert_eq!(red.lerp(green, 0.5), Color::new(128, 128, 0));
+        // half of the linear intensity of each channel, encoded back to sRGB
+        assert_eq!(red.lerp_linear(green, 0.5), Color::new(188, 188, 0));
+
+        assert_eq!(red.lerp_linear(green, 0.0), red);
+        assert_eq!(red.lerp_linear(green, 1.0), green);
+    }
+
+    #[test]
+    fn save_ref_keeps_canvas_usable() {
+        let dir = std::env::temp_dir();
+        let first = dir.join(format!("rtracer-save-ref-{}-a.png", std::process::id()));
+        let second = dir.join(format!("rtracer-save-ref-{}-b.png", std::process::id()));
+
+        let mut canvas = Canvas::new(4, 4);
+        canvas.save_ref(first.to_str().unwrap()).unwrap();
+        canvas.draw(1, 1, Color::new(255, 0, 0));
+        canvas.save_ref(second.to_str().unwrap()).unwrap();
+
+        let first_image = image::open(&first).unwrap();
+        let second_image = image::open(&second).unwrap();
+        std::fs::remove_file(&first).unwrap();
+        std::fs::remove_file(&second).unwrap();
+
+        assert_eq!(first_image.get_pixel(1, 1)[3], 0);
+        assert_eq!(second_image.get_pixel(1, 1).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn renderables_downcast_to_concrete_type() {
+        let mut scene = Scene::new();
+        scene.add(Sphere::new([1.0, 2.0, 3.0], 4.0));
+        scene.add(Cuboid::new([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]));
+
+        let sphere = scene.renderables[0].as_any().downcast_ref::<Sphere>();
+        assert_eq!(sphere, Some(&Sphere::new([1.0, 2.0, 3.0], 4.0)));
+        assert!(scene.renderables[1]
+            .as_any()
+            .downcast_ref::<Sphere>()
+            .is_none());
+    }
+
+    /// Fires rays at `renderable` from a grid of origins around the world origin, in the axis and diagonal directions, and again from inside every interval found
+    ///
+    /// Shapes placed on grid coordinates get rays that start on their surface, graze their edges and run parallel to their faces.
+    /// Checks that intervals are sorted and free of NaN, that `intersect` agrees with them and that every boundary lies on the surface given by the signed `distance` function
+    fn assert_renderable_invariants(renderable: &dyn Renderable, distance: impl Fn(Vec3) -> f32) {
+        let mut directions = Vec::new();
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    if (x, y, z) != (0, 0, 0) {
+                        directions.push(Vec3::new(x as f32, y as f32, z as f32).normalized());
+                    }
+                }
+            }
+        }
+
+        let mut rays = Vec::new();
+        for x in -2..=2 {
+            for y in -2..=2 {
+                for z in -2..=2 {
+                    let origin = Vec3::new(x as f32, y as f32, z as f32) * 10.0;
+                    rays.extend(
+                        directions
+                            .iter()
+                            .map(|&direction| Ray::new(origin, direction)),
+                    );
+                }
+            }
+        }
+
+        let check = |ray: &Ray| -> Vec<Interval> {
+            let intervals = renderable.intervals(ray);
+            for interval in &intervals {
+                assert!(
+                    !interval.enter.is_nan()
+                        && !interval.exit.is_nan()
+                        && interval.enter <= interval.exit,
+                    "invalid interval {:?} for {:?}",
+                    interval,
+                    ray
+                );
+                for t in [interval.enter, interval.exit] {
+                    if t.is_finite() {
+                        let off_surface = distance(ray.at(t)).abs();
+                        assert!(
+                            off_surface < 1e-3,
+                            "boundary at {} is {} off the surface for {:?}",
+                            t,
+                            off_surface,
+                            ray
+                        );
+                    }
+                }
+            }
+            for pair in intervals.windows(2) {
+                assert!(
+                    pair[0].exit <= pair[1].enter,
+                    "unsorted intervals {:?} for {:?}",
+                    intervals,
+                    ray
+                );
+            }
+
+            let hit = renderable.intersect(ray);
+            assert_eq!(
+                hit,
+                nearest_hit(&intervals),
+                "intersect disagrees with intervals for {:?}",
+                ray
+            );
+            if let Some(t) = hit {
+                assert!(
+                    t.is_finite() && t >= 0.0,
+                    "invalid hit distance {} for {:?}",
+                    t,
+                    ray
+                );
+            }
+            intervals
+        };
+
+        for ray in &rays {
+            for interval in check(ray) {
+                if !interval.enter.is_finite()
+                    || !interval.exit.is_finite()
+                    || interval.exit - interval.enter < 1e-2
+                {
+                    continue;
+                }
+
+                // from inside, the closest surface ahead is where the interval ends
+                let middle = (interval.enter + interval.exit) / 2.0;
+                let inside = Ray::new(ray.at(middle), ray.direction());
+                check(&inside);
+                let t = renderable.intersect(&inside);
+                assert!(
+                    t.is_some_and(|t| (t - (interval.exit - middle)).abs() < 1e-3),
+                    "ray {:?} from inside hits at {:?}, expected {}",
+                    inside,
+                    t,
+                    interval.exit - middle
+                );
+                for &direction in &directions {
+                    check(&Ray::new(inside.origin(), direction));
+                }
+            }
+        }
+    }
+
+    fn sphere_distance(sphere: Sphere) -> impl Fn(Vec3) -> f32 {
+        move |p| (p - sphere.center).magnitude() - sphere.radius
+    }
+
+    fn cuboid_distance(cuboid: Cuboid) -> impl Fn(Vec3) -> f32 {
+        move |p| {
+            let center = (cuboid.min + cuboid.max) / 2.0;
+            let half = (cuboid.max - cuboid.min) / 2.0;
+            let q = Vec3::new(
+                (p.x - center.x).abs() - half.x,
+                (p.y - center.y).abs() - half.y,
+                (p.z - center.z).abs() - half.z,
+            );
+            let outside = Vec3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).magnitude();
+            outside + q.x.max(q.y).max(q.z).min(0.0)
+        }
+    }
+
+    fn half_space_distance(half_space: HalfSpace) -> impl Fn(Vec3) -> f32 {
+        move |p| (p - half_space.point).dot(half_space.normal)
+    }
+
+    fn slab_distance(slab: Slab) -> impl Fn(Vec3) -> f32 {
+        move |p| {
+            let d = (p - slab.point).dot(slab.normal);
+            (-d).max(d - slab.thickness)
+        }
+    }
+
+    #[test]
+    fn sphere_invariants() {
+        let sphere = Sphere::new([0.0, 0.0, 0.0], 10.0);
+        assert_renderable_invariants(&sphere, sphere_distance(sphere));
+    }
+
+    #[test]
+    fn cuboid_invariants() {
+        let cuboid = Cuboid::new([-10.0, -10.0, -10.0], [10.0, 10.0, 10.0]);
+        assert_renderable_invariants(&cuboid, cuboid_distance(cuboid));
+    }
+
+    #[test]
+    fn half_space_invariants() {
+        let half_space = HalfSpace::new([0.0, 0.0, 0.0], [0.0, 1.0, 0.0]);
+        assert_renderable_invariants(&half_space, half_space_distance(half_space));
+        let tilted = HalfSpace::new([0.0, 10.0, 0.0], [1.0, 1.0, 0.0]);
+        assert_renderable_invariants(&tilted, half_space_distance(tilted));
+    }
+
+    #[test]
+    fn slab_invariants() {
+        let slab = Slab::new([0.0, -10.0, 0.0], [0.0, 1.0, 0.0], 20.0);
+        assert_renderable_invariants(&slab, slab_distance(slab));
+    }
+
+    #[test]
+    fn csg_invariants() {
+        let cuboid = Cuboid::new([-10.0, -10.0, -10.0], [10.0, 10.0, 10.0]);
+        let sphere = Sphere::new([10.0, 10.0, 0.0], 8.0);
+        let slab = Slab::new([0.0, -5.0, 0.0], [0.0, 1.0, 0.0], 10.0);
+
+        let (cuboid_d, sphere_d) = (cuboid_distance(cuboid), sphere_distance(sphere));
+        assert_renderable_invariants(&Csg::new(CsgOp::Difference, cuboid, sphere), |p| {
+            cuboid_d(p).max(-sphere_d(p))
+        });
+        assert_renderable_invariants(&Csg::new(CsgOp::Union, cuboid, sphere), |p| {
+            cuboid_d(p).min(sphere_d(p))
+        });
+
+        let (big_sphere, slab_d) = (Sphere::new([0.0, 0.0, 0.0], 12.0), slab_distance(slab));
+        let big_sphere_d = sphere_distance(big_sphere);
+        let sliced = Csg::new(CsgOp::Intersection, big_sphere, slab);
+        assert_renderable_invariants(&sliced, |p| big_sphere_d(p).max(slab_d(p)));
+
+        let nested = Csg::new(CsgOp::Difference, sliced, cuboid);
+        assert_renderable_invariants(&nested, |p| {
+            big_sphere_d(p).max(slab_d(p)).max(-cuboid_d(p))
+        });
+    }
+
+    #[test]
+    fn depth_pass_measures_distance_to_hit() {
+        let mut scene = Scene::new();
+        scene.add(Sphere::new([10.0, 10.0, 100.0], 20.0));
+        let raytracer = Raytracer::new(scene);
+
+        let depth = raytracer.render_depth((40, 40));
+        assert_eq!(depth.len(), 40 * 40);
+        // straight at the center of the sphere, the closest hit is one radius in front of it
+        assert_eq!(depth[10 * 40 + 10], 80.0);
+        assert_eq!(depth[10 * 40 + 39], f32::INFINITY);
+
+        let mut encoded = Vec::new();
+        encode_depth_png((40, 40), &depth, (0.0, 100.0), &mut encoded).unwrap();
+        let decoded = image::load_from_memory(&encoded).unwrap();
+        assert_eq!(decoded.color(), image::ColorType::L16);
+        let decoded = decoded.to_luma16();
+        assert_eq!(decoded.get_pixel(10, 10).0, [52428]);
+        assert_eq!(decoded.get_pixel(39, 10).0, [u16::MAX]);
+    }
+
+    #[test]
+    fn dithering_breaks_up_gradient_bands() {
+        // a gradient spanning only a few byte values, so that every band covers many rows
+        let background = Background::Gradient {
+            top: Color::new(100, 100, 100),
+            bottom: Color::new(104, 104, 104),
+        };
+        let render = |dither| {
+            let raytracer = Raytracer {
+                background,
+                dither,
+                ..Raytracer::default()
+            };
+            let mut canvas = Canvas::new(16, 64);
+            raytracer.render(&mut canvas);
+            canvas
+        };
+        let distinct_per_row = |canvas: &Canvas| -> Vec<usize> {
+            (0..64)
+                .map(|y| {
+                    let mut values: Vec<u8> =
+                        (0..16).map(|x| canvas.image.get_pixel(x, y)[0]).collect();
+                    values.sort_unstable();
+                    values.dedup();
+                    values.len()
+                })
+                .collect()
+        };
+
+        let plain = distinct_per_row(&render(false));
+        let dithered_canvas = render(true);
+        let dithered = distinct_per_row(&dithered_canvas);
+        assert!(plain.iter().all(|&count| count == 1));
+        assert!(dithered.iter().sum::<usize>() > plain.iter().sum::<usize>());
+
+        // the dither only moves the rounding error around, so the average brightness stays the same
+        let mean = |canvas: &Canvas| {
+            (0..64)
+                .flat_map(|y| (0..16).map(move |x| (x, y)))
+                .map(|(x, y)| canvas.image.get_pixel(x, y)[0] as f32)
+                .sum::<f32>()
+                / (16.0 * 64.0)
+        };
+        let exact = (0..64)
+            .map(|y| {
+                let t = y as f32 / 64.0;
+                let linear = srgb_to_linear(100) * (1.0 - t) + srgb_to_linear(104) * t;
+                let srgb = 1.055 * linear.powf(1.0 / 2.4) - 0.055;
+                srgb * 255.0
+            })
+            .sum::<f32>()
+            / 64.0;
+        assert!((mean(&dithered_canvas) - exact).abs() < 0.1);
+    }
+
+    #[test]
+    fn csg_union_merges_touching_operands_in_either_order() {
+        let a = Cuboid::new([0.0, -1.0, -1.0], [5.0, 1.0, 1.0]);
+        let b = Cuboid::new([5.0, -1.0, -1.0], [10.0, 1.0, 1.0]);
+        let forward = Ray::new([2.0, 0.0, 0.0], [1.0, 0.0, 0.0]);
+        let backward = Ray::new([8.0, 0.0, 0.0], [-1.0, 0.0, 0.0]);
+
+        for csg in [Csg::new(CsgOp::Union, a, b), Csg::new(CsgOp::Union, b, a)] {
+            assert_eq!(csg.intervals(&forward), vec![Interval::new(-2.0, 8.0)]);
+            assert_eq!(csg.intersect(&forward), Some(8.0));
+            assert_eq!(csg.intervals(&backward), vec![Interval::new(-2.0, 8.0)]);
+            assert_eq!(csg.intersect(&backward), Some(8.0));
+        }
+
+        // only sharing a face, the operands have no volume in common
+        for csg in [
+            Csg::new(CsgOp::Intersection, a, b),
+            Csg::new(CsgOp::Intersection, b, a),
+        ] {
+            assert_eq!(csg.intervals(&forward), Vec::new());
+        }
+    }
+
+    #[test]
+    fn csg_exposes_operands() {
+        let sphere = Sphere::new([0.0, 0.0, 10.0], 5.0);
+        let cuboid = Cuboid::new([-1.0, -1.0, 0.0], [1.0, 1.0, 20.0]);
+        let csg = Csg::new(CsgOp::Difference, sphere, cuboid);
+
+        assert_eq!(csg.op(), CsgOp::Difference);
+        assert_eq!(csg.left().as_any().downcast_ref::<Sphere>(), Some(&sphere));
+        assert_eq!(csg.right().as_any().downcast_ref::<Cuboid>(), Some(&cuboid));
+    }
+
+    #[test]
+    fn invalid_pixel_aspects_are_rejected() {
+        for pixel_aspect in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let raytracer = Raytracer {
+                pixel_aspect,
+                ..Raytracer::default()
+            };
+            let render = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                raytracer.render(&mut Canvas::new(4, 4))
+            }));
+            assert!(
+                render.is_err(),
+                "pixel aspect {} was accepted",
+                pixel_aspect
+            );
+            let depth =
+                std::panic::catch_unwind(AssertUnwindSafe(|| raytracer.render_depth((4, 4))));
+            assert!(depth.is_err(), "pixel aspect {} was accepted", pixel_aspect);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid pixel aspect 0")]
+    fn render_into_rejects_zero_pixel_aspect() {
+        let raytracer = Raytracer {
+            pixel_aspect: 0.0,
+            ..Raytracer::default()
+        };
+        let _ = raytracer.render_into((2, 2), &mut [Color::new(0, 0, 0); 4]);
+    }
+}